@@ -1,7 +1,13 @@
+mod profile;
 mod util;
 
+use std::path::PathBuf;
+
 use lz4_flex::block::DecompressError;
-use util::{decompress_lz4, list_recovery_files};
+use util::{atomic_write, compress_mozlz4, decompress_lz4};
+
+pub use profile::{list_profiles, Profile};
+pub use util::{list_recovery_files_with_depth, list_sessions, SessionFile, SessionVariant};
 
 /// Crate global errors
 #[derive(thiserror::Error, Debug)]
@@ -21,40 +27,160 @@ pub enum Error {
     /// Composed error; e.g. if list_tabs() failed trying multiple recovery files
     #[error("multiple errors: {0}")]
     Multi(String),
+    /// First 8 bytes of a session file were not the `mozLz40\0` magic
+    #[error("not a mozLz4 file: bad magic")]
+    InvalidMozLz4Magic,
+    /// A tab's `index` pointed outside its own `entries`; the session file is corrupt
+    #[error("invalid tab entry index {0} (have {1} entries)")]
+    InvalidEntryIndex(usize, usize),
+    /// A window or tab index passed to a `Session` accessor doesn't exist
+    #[error("index out of bounds: {0}")]
+    IndexOutOfBounds(&'static str),
 }
 
 /// Firefox Result
 pub type FFResult<T> = Result<T, Error>;
 
 mod recovery {
-    use serde::Deserialize;
+    use serde::{Deserialize, Serialize};
+    use serde_json::{Map, Value};
+
+    use crate::{Error, FFResult};
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Serialize, Debug, Clone)]
     pub struct TopLevel {
         pub windows: Vec<Window>,
+        /// Everything we don't model (`selectedWindow`, `session`, …),
+        /// round-tripped verbatim so `Session::save` never drops it.
+        #[serde(flatten)]
+        pub extra: Map<String, Value>,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Serialize, Debug, Clone)]
     pub struct Window {
         pub tabs: Vec<Tab>,
+        /// Tabs the user closed in this window, kept so they can be reopened
+        #[serde(rename = "_closedTabs", default)]
+        pub closed_tabs: Vec<ClosedTab>,
+        pub width: Option<u32>,
+        pub height: Option<u32>,
+        #[serde(rename = "screenX")]
+        pub screen_x: Option<i32>,
+        #[serde(rename = "screenY")]
+        pub screen_y: Option<i32>,
+        /// Everything we don't model (`selected`, `busy`, `title`, …),
+        /// round-tripped verbatim so `Session::save` never drops it.
+        #[serde(flatten)]
+        pub extra: Map<String, Value>,
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Serialize, Debug, Clone)]
     pub struct Tab {
         entries: Vec<Entry>,
         index: usize,
+        #[serde(rename = "lastAccessed", default)]
+        pub last_accessed: Option<u64>,
+        #[serde(default)]
+        pub pinned: bool,
+        #[serde(default)]
+        pub hidden: bool,
+        /// Everything we don't model (`userContextId`, `requestedIndex`, …),
+        /// round-tripped verbatim so `Session::save` never drops it.
+        #[serde(flatten)]
+        pub extra: Map<String, Value>,
     }
 
     impl Tab {
-        pub fn into_entry(mut self) -> Entry {
-            self.entries.swap_remove(self.index - 1)
+        /// Firefox's `index` is 1-based and, in a corrupt file, can point
+        /// past `entries.len()`; this fails with a typed error instead of
+        /// panicking on `swap_remove`.
+        pub fn into_entry(mut self) -> FFResult<Entry> {
+            let i = self.entry_index()?;
+            Ok(self.entries.swap_remove(i))
+        }
+
+        /// Borrowing counterpart of [`Tab::into_entry`], for callers (like
+        /// `Session`) that need to inspect tabs without consuming them.
+        pub fn entry(&self) -> FFResult<&Entry> {
+            let i = self.entry_index()?;
+            Ok(&self.entries[i])
+        }
+
+        fn entry_index(&self) -> FFResult<usize> {
+            self.index
+                .checked_sub(1)
+                .filter(|&i| i < self.entries.len())
+                .ok_or(Error::InvalidEntryIndex(self.index, self.entries.len()))
         }
     }
 
-    #[derive(Deserialize, Debug)]
+    #[derive(Deserialize, Serialize, Debug, Clone)]
     pub struct Entry {
         pub title: String,
         pub url: String,
+        /// Everything we don't model (`docshellUUID`, `scroll`, …),
+        /// round-tripped verbatim so `Session::save` never drops it.
+        #[serde(flatten)]
+        pub extra: Map<String, Value>,
+    }
+
+    /// A tab closed by the user; Firefox keeps its full state (history,
+    /// pinned/hidden flags, etc.) so it can be reopened.
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct ClosedTab {
+        pub title: String,
+        #[serde(rename = "closedAt", default)]
+        pub closed_at: Option<u64>,
+        pub state: Tab,
+        /// Everything we don't model, round-tripped verbatim so
+        /// `Session::save` never drops it.
+        #[serde(flatten)]
+        pub extra: Map<String, Value>,
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        fn entry(title: &str) -> Entry {
+            Entry {
+                title: title.to_string(),
+                url: format!("https://example.com/{title}"),
+                extra: Map::new(),
+            }
+        }
+
+        fn tab(entries: Vec<Entry>, index: usize) -> Tab {
+            Tab {
+                entries,
+                index,
+                last_accessed: None,
+                pinned: false,
+                hidden: false,
+                extra: Map::new(),
+            }
+        }
+
+        #[test]
+        fn into_entry_rejects_zero_index_instead_of_panicking() {
+            let tab = tab(vec![entry("a")], 0);
+            assert!(matches!(
+                tab.into_entry(),
+                Err(Error::InvalidEntryIndex(0, 1))
+            ));
+        }
+
+        #[test]
+        fn entry_rejects_out_of_bounds_index_instead_of_panicking() {
+            let tab = tab(vec![entry("a"), entry("b")], 5);
+            assert!(matches!(tab.entry(), Err(Error::InvalidEntryIndex(5, 2))));
+        }
+
+        #[test]
+        fn into_entry_accepts_valid_one_based_index() {
+            let tab = tab(vec![entry("a"), entry("b")], 2);
+            assert_eq!(tab.into_entry().unwrap().title, "b");
+        }
     }
 }
 
@@ -65,15 +191,12 @@ pub struct Tab {
     pub title: String,
     /// Tab's url
     pub url: String,
-}
-
-impl From<recovery::Entry> for Tab {
-    fn from(e: recovery::Entry) -> Self {
-        Tab {
-            title: e.title,
-            url: e.url,
-        }
-    }
+    /// Whether the tab is pinned
+    pub pinned: bool,
+    /// Whether the tab is hidden (e.g. tucked inside a tab group)
+    pub hidden: bool,
+    /// Milliseconds since the epoch this tab was last focused
+    pub last_accessed: Option<u64>,
 }
 
 impl Tab {
@@ -85,6 +208,135 @@ impl Tab {
     }
 }
 
+/// A window's tabs, closed tabs, and last known on-screen geometry.
+#[derive(Debug)]
+pub struct Window {
+    /// Currently open tabs, in tab-strip order
+    pub tabs: Vec<Tab>,
+    /// Tabs the user closed, most-recently-closed last
+    pub closed_tabs: Vec<Tab>,
+    /// Last known window width, when Firefox recorded one
+    pub width: Option<u32>,
+    /// Last known window height, when Firefox recorded one
+    pub height: Option<u32>,
+    /// Last known window horizontal screen position, when Firefox recorded one
+    pub screen_x: Option<i32>,
+    /// Last known window vertical screen position, when Firefox recorded one
+    pub screen_y: Option<i32>,
+}
+
+impl TryFrom<&recovery::Tab> for Tab {
+    type Error = Error;
+
+    fn try_from(tab: &recovery::Tab) -> FFResult<Self> {
+        let entry = tab.entry()?;
+        Ok(Tab {
+            title: entry.title.clone(),
+            url: entry.url.clone(),
+            pinned: tab.pinned,
+            hidden: tab.hidden,
+            last_accessed: tab.last_accessed,
+        })
+    }
+}
+
+impl TryFrom<&recovery::Window> for Window {
+    type Error = Error;
+
+    fn try_from(window: &recovery::Window) -> FFResult<Self> {
+        Ok(Window {
+            tabs: window
+                .tabs
+                .iter()
+                .map(Tab::try_from)
+                .collect::<FFResult<_>>()?,
+            closed_tabs: window
+                .closed_tabs
+                .iter()
+                .map(|closed| Tab::try_from(&closed.state))
+                .collect::<FFResult<_>>()?,
+            width: window.width,
+            height: window.height,
+            screen_x: window.screen_x,
+            screen_y: window.screen_y,
+        })
+    }
+}
+
+/// A session loaded from a `recovery.js*` file, editable and savable back to
+/// disk.
+#[derive(Debug)]
+pub struct Session {
+    path: PathBuf,
+    inner: recovery::TopLevel,
+}
+
+impl Session {
+    /// Loads the session stored at `path`.
+    pub fn load(path: impl Into<PathBuf>) -> FFResult<Self> {
+        let path = path.into();
+        let buf = decompress_lz4(&path)?;
+        let inner = serde_json::from_slice(&buf)?;
+        Ok(Session { path, inner })
+    }
+
+    /// Every window in the session, with its live tabs, recently-closed
+    /// tabs, and geometry.
+    pub fn windows(&self) -> FFResult<Vec<Window>> {
+        self.inner.windows.iter().map(Window::try_from).collect()
+    }
+
+    /// Flattened view of every live tab across every window, in the same
+    /// order as [`list_tabs`].
+    pub fn tabs(&self) -> FFResult<Vec<Tab>> {
+        self.inner
+            .windows
+            .iter()
+            .flat_map(|window| window.tabs.iter())
+            .map(Tab::try_from)
+            .collect()
+    }
+
+    /// Removes the tab at `tab_idx` from window `window_idx`.
+    pub fn remove_tab(&mut self, window_idx: usize, tab_idx: usize) -> FFResult<()> {
+        let window = self
+            .inner
+            .windows
+            .get_mut(window_idx)
+            .ok_or(Error::IndexOutOfBounds("window index"))?;
+        if tab_idx >= window.tabs.len() {
+            return Err(Error::IndexOutOfBounds("tab index"));
+        }
+        window.tabs.remove(tab_idx);
+        Ok(())
+    }
+
+    /// Moves the tab at `from` to `to` within window `window_idx`, shifting
+    /// the tabs in between over by one (same semantics as dragging a tab to
+    /// a new position in the tab strip).
+    pub fn move_tab(&mut self, window_idx: usize, from: usize, to: usize) -> FFResult<()> {
+        let window = self
+            .inner
+            .windows
+            .get_mut(window_idx)
+            .ok_or(Error::IndexOutOfBounds("window index"))?;
+        if from >= window.tabs.len() || to >= window.tabs.len() {
+            return Err(Error::IndexOutOfBounds("tab index"));
+        }
+        let tab = window.tabs.remove(from);
+        window.tabs.insert(to, tab);
+        Ok(())
+    }
+
+    /// Persists the (possibly edited) session back to the file it was loaded
+    /// from, atomically.
+    pub fn save(&self) -> FFResult<()> {
+        let json = serde_json::to_vec(&self.inner)?;
+        let compressed = compress_mozlz4(&json);
+        atomic_write(&self.path, &compressed)
+    }
+}
+
 /// Returns list of tabs in open firefox instance
 pub fn list_tabs() -> FFResult<Vec<Tab>> {
     let mut errors = Vec::with_capacity(0);
@@ -100,22 +352,34 @@ pub fn list_tabs() -> FFResult<Vec<Tab>> {
             }
         };
     }
-    for path_res in list_recovery_files()? {
-        let path = path_res?;
-
+    // newest-first: a partially-written newest file transparently falls back
+    // to the next freshest one
+    for session_file in list_sessions()? {
         // decompression and deserialization are errors that cause to skip this path
         // -- not causing to cancel list_tabs()
-        let buf = try_add!(decompress_lz4(path));
+        let buf = try_add!(decompress_lz4(session_file.path));
         let topl: recovery::TopLevel = try_add!(serde_json::from_slice(&buf));
 
-        // this should be error free
-        // TODO: if index is out of bounds in recovery.json -- this crashes
-        let tabs = topl
+        // a tab with an out-of-bounds `index` also just skips this file, same
+        // as a decompression/deserialization failure above
+        let tabs: FFResult<Vec<Tab>> = topl
             .windows
             .into_iter()
-            .flat_map(|window| window.tabs.into_iter().map(recovery::Tab::into_entry))
-            .map(Tab::from)
+            .flat_map(|window| window.tabs.into_iter())
+            .map(|tab| {
+                let pinned = tab.pinned;
+                let hidden = tab.hidden;
+                let last_accessed = tab.last_accessed;
+                tab.into_entry().map(|entry| Tab {
+                    title: entry.title,
+                    url: entry.url,
+                    pinned,
+                    hidden,
+                    last_accessed,
+                })
+            })
             .collect();
+        let tabs = try_add!(tabs);
         return Ok(tabs);
     }
 
@@ -134,4 +398,145 @@ pub fn list_tabs() -> FFResult<Vec<Tab>> {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Unique-enough path under the OS temp dir; cheaper than depending on
+    /// a `tempfile` crate for a couple of on-disk round-trip tests.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "firefox-rs-test-{}-{}-{name}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ))
+    }
+
+    fn write_session_file(path: &PathBuf, json: &serde_json::Value) {
+        let compressed = compress_mozlz4(&serde_json::to_vec(json).unwrap());
+        fs::write(path, compressed).unwrap();
+    }
+
+    #[test]
+    fn session_save_round_trips_unmodeled_fields() {
+        // fields we don't model: selectedWindow/session at the top level,
+        // selected/busy/title per window, userContextId per tab, and
+        // docshellUUID/scroll per entry
+        let fixture = json!({
+            "windows": [{
+                "tabs": [{
+                    "entries": [{
+                        "title": "Example",
+                        "url": "https://example.com",
+                        "docshellUUID": "abc-123",
+                        "scroll": "0,42"
+                    }],
+                    "index": 1,
+                    "userContextId": 7
+                }],
+                "selected": 1,
+                "busy": false,
+                "title": "My Window"
+            }],
+            "selectedWindow": 1,
+            "session": { "lastUpdate": 1690000000000_i64, "startTime": 1689999000000_i64 }
+        });
+
+        let path = temp_path("round-trip.jsonlz4");
+        write_session_file(&path, &fixture);
+
+        let session = Session::load(&path).unwrap();
+        // no edits: save() should still faithfully round-trip the fields we
+        // don't model, rather than silently dropping them
+        session.save().unwrap();
+
+        let saved = decompress_lz4(&path).unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&saved).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(saved["selectedWindow"], 1);
+        assert_eq!(saved["session"]["lastUpdate"], 1690000000000_i64);
+        assert_eq!(saved["session"]["startTime"], 1689999000000_i64);
+        assert_eq!(saved["windows"][0]["selected"], 1);
+        assert_eq!(saved["windows"][0]["busy"], false);
+        assert_eq!(saved["windows"][0]["title"], "My Window");
+        assert_eq!(saved["windows"][0]["tabs"][0]["userContextId"], 7);
+        let entry = &saved["windows"][0]["tabs"][0]["entries"][0];
+        assert_eq!(entry["docshellUUID"], "abc-123");
+        assert_eq!(entry["scroll"], "0,42");
+    }
+
+    #[test]
+    fn session_load_mutate_save_reload_preserves_extra_fields_and_edit() {
+        let fixture = json!({
+            "windows": [{
+                "tabs": [
+                    {
+                        "entries": [{"title": "a", "url": "https://a.example", "docshellUUID": "u-a"}],
+                        "index": 1
+                    },
+                    {
+                        "entries": [{"title": "b", "url": "https://b.example", "docshellUUID": "u-b"}],
+                        "index": 1
+                    }
+                ],
+                "title": "My Window"
+            }]
+        });
+
+        let path = temp_path("mutate-reload.jsonlz4");
+        write_session_file(&path, &fixture);
+
+        let mut session = Session::load(&path).unwrap();
+        session.remove_tab(0, 0).unwrap();
+        session.save().unwrap();
+
+        // the window-level extra field ("title") must have survived the edit
+        let saved = decompress_lz4(&path).unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&saved).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(saved["windows"][0]["title"], "My Window");
+        let remaining_tabs = saved["windows"][0]["tabs"].as_array().unwrap();
+        assert_eq!(remaining_tabs.len(), 1);
+        assert_eq!(remaining_tabs[0]["entries"][0]["title"], "b");
+        assert_eq!(remaining_tabs[0]["entries"][0]["docshellUUID"], "u-b");
+    }
+
+    #[test]
+    fn atomic_write_leaves_target_with_exact_bytes_and_no_tmp_file() {
+        let path = temp_path("atomic-write.bin");
+        let data = b"hello mozlz4 world";
+
+        atomic_write(&path, data).unwrap();
+        let on_disk = fs::read(&path).unwrap();
+
+        let tmp_path = path
+            .parent()
+            .unwrap()
+            .join(format!(".{}.tmp", path.file_name().unwrap().to_str().unwrap()));
+        let tmp_survived = tmp_path.exists();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(on_disk, data);
+        assert!(!tmp_survived);
+    }
+
+    #[test]
+    fn compress_mozlz4_round_trips_through_decompress_lz4() {
+        let path = temp_path("compress-round-trip.jsonlz4");
+        let original = b"{\"windows\":[]}".to_vec();
+
+        fs::write(&path, compress_mozlz4(&original)).unwrap();
+        let decompressed = decompress_lz4(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(decompressed, original);
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, FFResult};
+
+/// A profile entry resolved from `profiles.ini`
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Absolute path to the profile directory
+    pub path: PathBuf,
+    /// Whether this profile is the one Firefox launches into by default
+    pub default: bool,
+}
+
+type Section = HashMap<String, String>;
+
+/// Bare-bones `.ini` parser: just enough to read `profiles.ini`/`installs.ini`
+/// (`[Section]` headers, `key=value` pairs, `;`/`#` comments)
+fn parse_ini(content: &str) -> HashMap<String, Section> {
+    let mut sections: HashMap<String, Section> = HashMap::new();
+    let mut current = String::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            current = name.to_string();
+            sections.entry(current.clone()).or_default();
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            sections
+                .entry(current.clone())
+                .or_default()
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+/// Returns the Firefox data root for the current OS, analogous to how the
+/// crash reporter resolves its own data dir from the Vendor/Product keys:
+///
+/// - Linux: `~/.mozilla/firefox`
+/// - macOS: `~/Library/Application Support/Firefox`
+/// - Windows: `%APPDATA%\Mozilla\Firefox`
+pub fn firefox_root() -> FFResult<PathBuf> {
+    #[cfg(target_os = "macos")]
+    let root = dirs::home_dir().map(|home| home.join("Library/Application Support/Firefox"));
+
+    #[cfg(target_os = "windows")]
+    let root = dirs::config_dir().map(|dir| dir.join("Mozilla").join("Firefox"));
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let root = dirs::home_dir().map(|home| home.join(".mozilla/firefox"));
+
+    root.ok_or(Error::FFDirNotFound("home"))
+}
+
+/// Parses `profiles.ini` (and `installs.ini`'s `Default=` when present) rooted
+/// at `firefox_root` into the list of known profiles.
+pub fn list_profiles_in(firefox_root: impl AsRef<Path>) -> FFResult<Vec<Profile>> {
+    let firefox_root = firefox_root.as_ref();
+    let content = fs::read_to_string(firefox_root.join("profiles.ini"))?;
+    let sections = parse_ini(&content);
+
+    // an [InstallXXXXXXXX] section's Default= names the profile Path this
+    // particular installation actually starts with; it takes precedence over
+    // a profile's own Default=1 flag, which is only a profiles.ini-wide hint
+    let install_default = sections
+        .iter()
+        .find(|(name, _)| name.starts_with("Install"))
+        .and_then(|(_, section)| section.get("Default"));
+
+    let mut profiles = Vec::new();
+    for (name, section) in &sections {
+        if !name.starts_with("Profile") {
+            continue;
+        }
+        let Some(rel_path) = section.get("Path") else {
+            continue;
+        };
+        let is_relative = section
+            .get("IsRelative")
+            .map(|flag| flag == "1")
+            .unwrap_or(true);
+        let path = if is_relative {
+            firefox_root.join(rel_path)
+        } else {
+            PathBuf::from(rel_path)
+        };
+        let is_default = match install_default {
+            // an Install section is present: it alone decides the default,
+            // ignoring any (possibly stale) per-profile Default=1 flag
+            Some(default_path) => default_path == rel_path,
+            None => section
+                .get("Default")
+                .map(|flag| flag == "1")
+                .unwrap_or_default(),
+        };
+
+        profiles.push(Profile {
+            path,
+            default: is_default,
+        });
+    }
+
+    Ok(profiles)
+}
+
+/// Lists the profiles known to the local Firefox install.
+pub fn list_profiles() -> FFResult<Vec<Profile>> {
+    list_profiles_in(firefox_root()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_default_from_install_section() {
+        let profiles = list_profiles_in("assets/test/.mozilla/firefox").unwrap();
+        let default = profiles.iter().find(|p| p.default).unwrap();
+        assert!(default.path.ends_with("5w5airb6.default-release"));
+    }
+
+    #[test]
+    fn install_default_overrides_stale_profile_flag() {
+        // profileA carries a stale Default=1, but the Install section says
+        // profileB is the one actually in use; only profileB should win
+        let profiles = list_profiles_in("assets/test-stale-default/.mozilla/firefox").unwrap();
+        let defaults: Vec<_> = profiles.iter().filter(|p| p.default).collect();
+        assert_eq!(defaults.len(), 1);
+        assert!(defaults[0].path.ends_with("profileB"));
+    }
+}
@@ -1,110 +1,168 @@
 use std::{
-    fs::{read_dir, File},
-    path::{Path, PathBuf}, io::Read,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
-use crate::{Error, FFResult};
-
-enum ErrorOrIterator<E, I> {
-    Error(Option<E>),
-    Iter(I),
+use walkdir::WalkDir;
+
+use crate::{profile, Error, FFResult};
+
+/// Magic bytes prefixing every mozLz4 container (`recovery.jsonlz4` et al.)
+const MOZLZ4_MAGIC: &[u8; 8] = b"mozLz40\0";
+
+/// The different session-store artifacts Firefox writes under
+/// `sessionstore-backups/`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionVariant {
+    /// `recovery.jsonlz4`: the live, continuously-updated session
+    Recovery,
+    /// `recovery.baklz4`: snapshot kept before the last rewrite of `Recovery`
+    RecoveryBackup,
+    /// `previous.jsonlz4`: the session from the last clean shutdown
+    Previous,
+    /// `upgrade.jsonlz4-<version>`: snapshot taken before a Firefox upgrade
+    Upgrade,
 }
 
-impl<T, E, I> Iterator for ErrorOrIterator<E, I>
-where
-    I: Iterator<Item = Result<T, E>>,
-{
-    type Item = I::Item;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        match self {
-            ErrorOrIterator::Error(opt) => opt.take().map(Err),
-            ErrorOrIterator::Iter(i) => i.next(),
+impl SessionVariant {
+    /// Classifies a `sessionstore-backups` file name, or `None` if it isn't
+    /// a session-store artifact we recognize.
+    fn classify(file_name: &str) -> Option<Self> {
+        if file_name.starts_with("recovery.jsonlz4") {
+            Some(Self::Recovery)
+        } else if file_name.starts_with("recovery.baklz4") {
+            Some(Self::RecoveryBackup)
+        } else if file_name.starts_with("previous.jsonlz4") {
+            Some(Self::Previous)
+        } else if file_name.starts_with("upgrade.jsonlz4-") {
+            Some(Self::Upgrade)
+        } else {
+            None
         }
     }
 }
 
-fn list_recovery_files_inner(
-    override_home: impl AsRef<Path>,
-) -> FFResult<impl Iterator<Item = Result<PathBuf, std::io::Error>>> {
-    // similar to (?), but returns ErrorOrIterator::Error on error
-    macro_rules! try_eoi {
-        ($result:expr) => {
-            match $result {
-                Ok(ok) => ok,
-                Err(e) => return Some(ErrorOrIterator::Error(Some(e))),
-            }
-        };
-    }
-
-    // similar to (?), but returns error inside an option (needed for filter_map)
-    macro_rules! try_some {
-        ($result:expr) => {
-            match $result {
-                Ok(ok) => ok,
-                Err(e) => return Some(Err(e)),
-            }
-        };
-    }
-
-    let firefox_root = override_home.as_ref().join(".mozilla/firefox");
-    // top level result (fails if firefox_root does not exist / is not accessible)
-    let iter = read_dir(firefox_root)?
-        .filter_map(|entry_res| {
-            // try_eoi! will exit closure with error in case of file system changes
-            // while transversing
-            let entry = try_eoi!(entry_res);
-            let is_default_dir = try_eoi!(entry.file_type()).is_dir()
-                && entry
-                    .file_name()
-                    .to_str()
-                    .map(|s| s.contains("default"))
-                    .unwrap_or_default();
-
-            // if not *default* dir, we are not interested
-            if !is_default_dir {
-                return None;
-            }
+/// A session-store file discovered on disk, classified by variant and
+/// carrying its last-modified time so candidates can be ranked by recency.
+#[derive(Debug, Clone)]
+pub struct SessionFile {
+    pub path: PathBuf,
+    pub variant: SessionVariant,
+    pub modified: SystemTime,
+}
 
-            // information should be inside sessionstore-backups
-            let backups = entry.path().join("sessionstore-backups");
+/// Depth (relative to the profile root) at which `sessionstore-backups`
+/// normally lives; the default for [`list_recovery_files`].
+const DEFAULT_MAX_DEPTH: usize = 2;
 
-            // it is possible (_acceptable_) that sessionstore-backups does not exist in a folder with
-            // *default* in the name; that's why it is not a hard error, but it's skipped: .ok()?
-            let iter = read_dir(backups).ok()?.filter_map(|entry_res| {
-                // this can also be considered transversal error
-                let entry = try_some!(entry_res);
-                let is_recovery_file = try_some!(entry.file_type()).is_file()
+fn list_recovery_files_inner(
+    firefox_root: impl AsRef<Path>,
+    max_depth: usize,
+) -> FFResult<impl Iterator<Item = Result<PathBuf, walkdir::Error>>> {
+    let profiles = profile::list_profiles_in(firefox_root)?;
+    let default_profile = profiles
+        .into_iter()
+        .find(|p| p.default)
+        .ok_or(Error::FFDirNotFound("default profile"))?;
+
+    // recurse from the profile root rather than hardcoding
+    // sessionstore-backups/ so callers can widen max_depth to scan the whole
+    // profile tree; per-entry errors (permission denied, a vanished dir)
+    // surface without aborting the rest of the walk
+    let iter = WalkDir::new(default_profile.path)
+        .max_depth(max_depth)
+        .into_iter()
+        .filter_map(|entry_res| match entry_res {
+            Ok(entry) => {
+                let is_session_file = entry.file_type().is_file()
                     && entry
                         .file_name()
                         .to_str()
-                        .map(|s| s.starts_with("recovery.js"))
+                        .map(|s| SessionVariant::classify(s).is_some())
                         .unwrap_or_default();
-
-                // if filename starts with recovery.js, return as possible path
-                is_recovery_file.then(|| Ok(entry.path()))
-            });
-            Some(ErrorOrIterator::Iter(iter))
-        })
-        // iterator of iterators, we only want paths
-        .flatten();
+                is_session_file.then(|| Ok(entry.into_path()))
+            }
+            Err(e) => Some(Err(e)),
+        });
     Ok(iter)
 }
 
-/// Returns iterator of viable recovery files
+/// Returns iterator of viable session-store files (any variant), descending
+/// at most `max_depth` levels from the default profile's root.
+pub fn list_recovery_files_with_depth(
+    max_depth: usize,
+) -> FFResult<impl Iterator<Item = Result<PathBuf, walkdir::Error>>> {
+    list_recovery_files_inner(profile::firefox_root()?, max_depth)
+}
+
+/// Returns iterator of viable session-store files (any variant)
 ///
-/// - Ok(Iterator<Result<PathBuf>>): each element of the iterator could fail in case file system changes occur while transversing
+/// - Ok(Iterator<Result<PathBuf>>): each element of the iterator could fail if an I/O error (e.g. permission denied) is hit mid-traversal
 /// - Err(_): in case mozila data dir is not found / is not accessible
-pub fn list_recovery_files() -> FFResult<impl Iterator<Item = Result<PathBuf, std::io::Error>>> {
-    let home_dir = dirs::home_dir().ok_or_else(|| Error::FFDirNotFound("home"))?;
-    list_recovery_files_inner(home_dir)
+pub fn list_recovery_files() -> FFResult<impl Iterator<Item = Result<PathBuf, walkdir::Error>>> {
+    list_recovery_files_with_depth(DEFAULT_MAX_DEPTH)
+}
+
+/// Returns every session-store file for the default profile, classified by
+/// variant and ordered newest-modified-first so callers can prefer the
+/// freshest valid one over whichever order the traversal happens to yield.
+pub fn list_sessions() -> FFResult<Vec<SessionFile>> {
+    let mut files: Vec<SessionFile> = list_recovery_files()?
+        .filter_map(|path_res| {
+            let path = path_res.ok()?;
+            let file_name = path.file_name()?.to_str()?;
+            let variant = SessionVariant::classify(file_name)?;
+            let modified = fs::metadata(&path).ok()?.modified().ok()?;
+            Some(SessionFile {
+                path,
+                variant,
+                modified,
+            })
+        })
+        .collect();
+    files.sort_by_key(|f| std::cmp::Reverse(f.modified));
+    Ok(files)
 }
 
 pub fn decompress_lz4(p: impl AsRef<Path>) -> FFResult<Vec<u8>> {
     let mut f = File::open(p)?;
     let mut buf = vec![];
     f.read_to_end(&mut buf)?;
-    Ok(lz4_flex::decompress_size_prepended(&buf[8..])?)
+    if buf.len() < MOZLZ4_MAGIC.len() || &buf[..MOZLZ4_MAGIC.len()] != MOZLZ4_MAGIC {
+        return Err(Error::InvalidMozLz4Magic);
+    }
+    Ok(lz4_flex::decompress_size_prepended(&buf[MOZLZ4_MAGIC.len()..])?)
+}
+
+/// Inverse of [`decompress_lz4`]: wraps `data` in the mozLz4 container
+/// (magic + `lz4_flex`-compressed, size-prepended body) ready to write to a
+/// `sessionstore-backups/*.jsonlz4` file.
+pub fn compress_mozlz4(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(MOZLZ4_MAGIC.len() + data.len());
+    out.extend_from_slice(MOZLZ4_MAGIC);
+    out.extend_from_slice(&lz4_flex::compress_prepend_size(data));
+    out
+}
+
+/// Writes `data` to `path` without ever leaving a truncated/corrupt file
+/// behind: the bytes land in a sibling temp file first, which is then
+/// `rename`d over `path` in a single syscall (atomic on the same filesystem).
+pub fn atomic_write(path: impl AsRef<Path>, data: &[u8]) -> FFResult<()> {
+    let path = path.as_ref();
+    let dir = path.parent().ok_or(Error::FFDirNotFound("parent dir"))?;
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(Error::FFDirNotFound("file name"))?;
+    let tmp_path = dir.join(format!(".{file_name}.tmp"));
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(data)?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
 }
 
 
@@ -116,15 +174,21 @@ mod test {
 
     #[test]
     fn list_recovery() {
-        let files: Vec<_> =list_recovery_files_inner("assets/test")
-            .unwrap()
-            .collect();
-        let expected = ["assets/test/.mozilla/firefox/5w5airb6.default-release/sessionstore-backups/recovery.jsonlz4"]; 
+        // fixture only needs to exist on disk with a recognized name; this
+        // test walks the tree and classifies file names, it never decompresses
+        let files: Vec<_> =
+            list_recovery_files_inner("assets/test/.mozilla/firefox", super::DEFAULT_MAX_DEPTH)
+                .unwrap()
+                .collect();
+        let expected = ["assets/test/.mozilla/firefox/5w5airb6.default-release/sessionstore-backups/recovery.jsonlz4"];
+        eprintln!("expected:{expected:?}");
+        eprintln!("files:{files:?}");
+        // zip() silently truncates to the shorter side, so a missing/extra
+        // file would otherwise pass vacuously; check the count first
+        assert_eq!(files.len(), expected.len());
         let fullmatch = files.iter()
             .zip(&expected)
             .all(|(listed, expected)| listed.as_ref().unwrap() == &PathBuf::from_str(expected).unwrap());
-        eprintln!("expected:{expected:?}");
-        eprintln!("files:{files:?}");
         assert!(fullmatch);
     }
 }